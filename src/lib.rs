@@ -5,7 +5,9 @@ use thiserror::Error;
 mod migrations;
 mod parameterize;
 
-pub use migrations::GraphMigrator;
+pub use migrations::{
+    GraphMigrator, MigrationManager, MigrationManagerBuilder, MigrationState, MigrationStatus,
+};
 pub use parameterize::parameterize;
 
 #[derive(Debug, Error)]
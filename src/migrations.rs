@@ -1,21 +1,43 @@
-use chrono::Utc;
-use neo4rs::{query, Database, Graph};
+use chrono::{NaiveDateTime, Utc};
+use neo4rs::{query, Database, Graph, Txn};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     fs,
+    future::Future,
     path::{Path, PathBuf},
+    pin::Pin,
 };
 use thiserror::Error;
 use tracing::{error, info};
 
-use crate::{get_single, parameterize::parameterize, QueryError};
+use crate::{all, get_single, parameterize::parameterize, QueryError};
 
 /// Represents a file migration discovered on disk.
+///
+/// A migration may carry a paired "down" script used to reverse it. The down
+/// script is optional - plain single-direction `.cyp` files leave it empty.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct FileMigration {
     pub checksum: String,
     pub file_name: String,
     pub cypher_text: String,
+    pub down_cypher: Option<String>,
+    pub down_checksum: Option<String>,
+    /// Checksum over the raw file contents, as recorded before comment
+    /// stripping was introduced. Kept so migrations applied under the old
+    /// scheme are still recognised instead of tripping the mismatch check.
+    #[serde(skip)]
+    pub legacy_checksum: String,
+}
+
+impl FileMigration {
+    /// Returns true if `recorded` matches either the normalized checksum or the
+    /// pre-comment-stripping (legacy) checksum, so databases migrated before the
+    /// checksum scheme changed continue to validate.
+    fn checksum_matches(&self, recorded: &str) -> bool {
+        recorded == self.checksum || recorded == self.legacy_checksum
+    }
 }
 
 /// Represents a migrated migration in the graph database.
@@ -24,10 +46,36 @@ pub struct MigrationsNode {
     pub checksum: String,
     pub file_name: String,
     pub cypher_text: String,
+    pub down_cypher: Option<String>,
+    pub down_checksum: Option<String>,
     pub version: u64,
     pub timestamp: chrono::DateTime<Utc>,
 }
 
+/// Classification of a single migration when comparing the files on disk
+/// against the tracking nodes recorded in the graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationState {
+    /// Applied and its checksum still matches the file on disk.
+    Applied,
+    /// Present on disk but not yet recorded in the graph.
+    Pending,
+    /// Applied, but the file on disk no longer matches the recorded checksum.
+    ChecksumDrift { expected: String, found: String },
+    /// Recorded in the graph but no longer present on disk.
+    Orphaned,
+}
+
+/// The state of one migration, pairing its name/version with its
+/// [`MigrationState`]. Returned by [`GraphMigrator::status`] so callers can
+/// build a plan, render a dashboard or fail CI on drift.
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub file_name: String,
+    pub version: Option<u64>,
+    pub state: MigrationState,
+}
+
 #[derive(Debug, Error)]
 pub enum MigrationError {
     #[error("Migration checksum was mismatched")]
@@ -38,6 +86,19 @@ pub enum MigrationError {
 
     #[error("Query error: {0}")]
     QueryError(#[from] QueryError),
+
+    #[error("Could not parse a version prefix from migration '{0}'")]
+    UnparseableVersion(String),
+
+    #[error("Duplicate migration version {0}")]
+    DuplicateVersion(u64),
+
+    #[error("Migration batch aborted at '{file_name}'")]
+    BatchAborted {
+        file_name: String,
+        #[source]
+        source: Box<MigrationError>,
+    },
 }
 
 /// GraphMigrator is used to run migrations from .cyp or .cypher files in a directory.
@@ -50,12 +111,16 @@ impl GraphMigrator {
     }
 
     /// Gathers all `.cyp` migrations from the specified folder.
+    ///
+    /// A migration is either a single `.cyp`/`.cypher` file (optionally carrying
+    /// a `-- UP` / `-- DOWN` delimiter) or a directory containing an `up.cyp` and
+    /// an optional `down.cyp`.
     pub fn gather_migrations(&self, folder_path: &Path) -> Vec<FileMigration> {
         // Collect migration files
         match fs::read_dir(folder_path) {
             Ok(entries) => entries
                 .filter_map(|entry| entry.ok()) // Filter out errors
-                .filter_map(|entry| self.process_file(entry.path())) // Process valid `.cyp` files
+                .filter_map(|entry| self.process_entry(entry.path())) // Process valid migrations
                 .collect(),
             Err(_) => vec![], // Return empty vector if folder can't be read
         }
@@ -67,20 +132,167 @@ impl GraphMigrator {
         driver: Graph,
         migrations: Vec<FileMigration>,
     ) -> Result<(), MigrationError> {
-        info!("Running migrations for {} files", migrations.len());
+        let ordered = self.order_migrations(migrations)?;
+
+        info!("Running migrations for {} files", ordered.len());
 
-        for (counter, migration) in migrations.iter().enumerate() {
-            self.up_migration(counter as u64, db.clone(), driver.clone(), migration)
+        let mut previous_version = 0u64;
+        for (version, migration) in &ordered {
+            self.up_migration(*version, previous_version, db.clone(), driver.clone(), migration)
                 .await?;
+            previous_version = *version;
         }
 
         Ok(())
     }
 
+    /// Parses and validates the version of every migration, returning them
+    /// sorted by ascending version.
+    ///
+    /// Filesystem iteration order is not stable, so we derive the version from
+    /// the leading prefix of each file name and sort on it. Duplicate or
+    /// unparseable prefixes are rejected so the resulting chain is reproducible.
+    fn order_migrations(
+        &self,
+        migrations: Vec<FileMigration>,
+    ) -> Result<Vec<(u64, FileMigration)>, MigrationError> {
+        let mut versioned = Vec::with_capacity(migrations.len());
+        let mut seen = std::collections::HashSet::new();
+
+        for migration in migrations {
+            let version = Self::parse_version(&migration.file_name)?;
+            if !seen.insert(version) {
+                return Err(MigrationError::DuplicateVersion(version));
+            }
+            versioned.push((version, migration));
+        }
+
+        versioned.sort_by_key(|(version, _)| *version);
+
+        Ok(versioned)
+    }
+
+    /// Parses the version prefix of a migration file name.
+    ///
+    /// Both the `%Y-%m-%d-%H%M%S` timestamp convention (e.g.
+    /// `2024-01-02-153000_create.cyp`) and plain integer prefixes (e.g.
+    /// `001_create.cyp`) are supported.
+    fn parse_version(file_name: &str) -> Result<u64, MigrationError> {
+        let stem = file_name.split('.').next().unwrap_or(file_name);
+        let prefix = stem.split('_').next().unwrap_or(stem);
+
+        if let Ok(version) = prefix.parse::<u64>() {
+            return Ok(version);
+        }
+
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(prefix, "%Y-%m-%d-%H%M%S") {
+            return Ok(timestamp.and_utc().timestamp() as u64);
+        }
+
+        Err(MigrationError::UnparseableVersion(file_name.to_string()))
+    }
+
+    /// Classifies a single file against its tracking node, if any.
+    ///
+    /// Kept pure so the comparison logic shared by [`status`](Self::status) can
+    /// be unit-tested without a database.
+    fn classify(migration: &FileMigration, existing: Option<&MigrationsNode>) -> MigrationState {
+        match existing {
+            None => MigrationState::Pending,
+            Some(node) if migration.checksum_matches(&node.checksum) => MigrationState::Applied,
+            Some(node) => MigrationState::ChecksumDrift {
+                expected: node.checksum.clone(),
+                found: migration.checksum.clone(),
+            },
+        }
+    }
+
+    /// Rolls back the most recently applied migrations.
+    ///
+    /// Walks the migration node chain backwards from the highest `version`,
+    /// executes up to `steps` nodes' stored `down_cypher` in reverse order and
+    /// deletes the matching `DataModelMigration` node once its down script runs.
+    pub async fn run_rollback(
+        &self,
+        db: Database,
+        driver: Graph,
+        steps: usize,
+    ) -> Result<(), MigrationError> {
+        let applied = self
+            .get_applied_migrations(db.clone(), driver.clone())
+            .await?;
+
+        info!(
+            "Rolling back {} of {} applied migrations",
+            steps.min(applied.len()),
+            applied.len()
+        );
+
+        for node in applied.into_iter().rev().take(steps) {
+            self.down_migration(db.clone(), driver.clone(), &node).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports the state of every migration without touching the database.
+    ///
+    /// Loads all tracking nodes in one query, then classifies each file on disk
+    /// as [`Applied`](MigrationState::Applied),
+    /// [`Pending`](MigrationState::Pending) or
+    /// [`ChecksumDrift`](MigrationState::ChecksumDrift), and flags any tracking
+    /// node with no matching file as [`Orphaned`](MigrationState::Orphaned). The
+    /// result is ordered by version so it can be rendered directly.
+    pub async fn status(
+        &self,
+        db: Database,
+        driver: Graph,
+        migrations: &[FileMigration],
+    ) -> Result<Vec<MigrationStatus>, MigrationError> {
+        let applied = self
+            .get_applied_migrations(db.clone(), driver.clone())
+            .await?;
+        let mut by_name: std::collections::HashMap<String, MigrationsNode> = applied
+            .into_iter()
+            .map(|node| (node.file_name.clone(), node))
+            .collect();
+
+        let mut statuses = Vec::new();
+        for migration in migrations {
+            let version = Self::parse_version(&migration.file_name).ok();
+            let state = Self::classify(migration, by_name.remove(&migration.file_name).as_ref());
+
+            statuses.push(MigrationStatus {
+                file_name: migration.file_name.clone(),
+                version,
+                state,
+            });
+        }
+
+        // Any tracking node left unmatched has no file backing it on disk.
+        for (_, node) in by_name {
+            statuses.push(MigrationStatus {
+                file_name: node.file_name,
+                version: Some(node.version),
+                state: MigrationState::Orphaned,
+            });
+        }
+
+        statuses.sort_by(|a, b| {
+            a.version
+                .unwrap_or(u64::MAX)
+                .cmp(&b.version.unwrap_or(u64::MAX))
+                .then_with(|| a.file_name.cmp(&b.file_name))
+        });
+
+        Ok(statuses)
+    }
+
     /// Checks if a migration exists already, and migrates it if it has not been migrated already.
     async fn up_migration(
         &self,
-        counter: u64,
+        version: u64,
+        previous_version: u64,
         db: Database,
         driver: Graph,
         migration: &FileMigration,
@@ -90,7 +302,7 @@ impl GraphMigrator {
             .await?;
 
         if let Some(existing_migration) = existing {
-            if existing_migration.checksum != migration.checksum {
+            if !migration.checksum_matches(&existing_migration.checksum) {
                 error!(
                     "[{}] CHECKSUM MISMATCH - wrong checksum ✘",
                     migration.file_name
@@ -102,7 +314,7 @@ impl GraphMigrator {
         }
 
         let _ = self
-            .create_migration_node(counter, db, driver, migration)
+            .create_migration_node(version, previous_version, db, driver, migration)
             .await?;
 
         info!("[{}] DONE - migrated ✓", migration.file_name);
@@ -110,38 +322,192 @@ impl GraphMigrator {
         Ok(())
     }
 
-    /// Actual migration in a transaction.
+    /// Reverses a single migration by running its stored `down_cypher` and
+    /// deleting the tracking node once it succeeds.
+    async fn down_migration(
+        &self,
+        db: Database,
+        driver: Graph,
+        node: &MigrationsNode,
+    ) -> Result<(), MigrationError> {
+        // The down script is usually DDL (`DROP CONSTRAINT` / `DROP INDEX`),
+        // which Neo4j will not let share a transaction with the tracking-node
+        // data write below, so run it and commit on its own first.
+        if let Some(down_cypher) = node.down_cypher.as_deref() {
+            let mut tx = driver.start_txn_on(db.clone()).await?;
+            for statement in self.split_statements(down_cypher) {
+                tx.run(query(statement.as_str())).await?;
+            }
+            tx.commit().await?;
+        } else {
+            info!(
+                "[{}] no down migration - only removing tracking node",
+                node.file_name
+            );
+        }
+
+        let mut tx = driver.start_txn_on(db.clone()).await?;
+        let delete_query =
+            query("MATCH (m:DataModelMigration { version: $version }) DETACH DELETE m")
+                .param("version", node.version as i64);
+        tx.run(delete_query).await?;
+        tx.commit().await?;
+
+        info!("[{}] ROLLED BACK ✓", node.file_name);
+
+        Ok(())
+    }
+
+    /// Runs the statements of all pending migrations inside a single
+    /// transaction, rolling the whole batch back if any migration fails.
+    ///
+    /// Unlike [`run_migrations`](Self::run_migrations), which commits each step
+    /// independently, the migration statements commit once, together: if a
+    /// later migration aborts, every migration applied earlier in the batch is
+    /// rolled back and the offending file name is reported via
+    /// [`MigrationError::BatchAborted`]. The tracking nodes are not part of that
+    /// transaction; they are written after it commits, each in its own
+    /// transaction, so a failure at that point leaves the batch applied but not
+    /// fully recorded.
+    pub async fn run_migrations_atomic(
+        &self,
+        db: Database,
+        driver: Graph,
+        migrations: Vec<FileMigration>,
+    ) -> Result<(), MigrationError> {
+        let ordered = self.order_migrations(migrations)?;
+        let applied = self
+            .get_applied_migrations(db.clone(), driver.clone())
+            .await?;
+        let applied_by_name: std::collections::HashMap<String, MigrationsNode> = applied
+            .into_iter()
+            .map(|node| (node.file_name.clone(), node))
+            .collect();
+
+        // Resolve the pending set up front, validating checksums of everything
+        // already applied so the chain cannot be linked onto a drifted node.
+        let mut plan = Vec::new();
+        let mut previous_version = 0u64;
+        for (version, migration) in ordered {
+            if let Some(existing) = applied_by_name.get(&migration.file_name) {
+                if !migration.checksum_matches(&existing.checksum) {
+                    error!(
+                        "[{}] CHECKSUM MISMATCH - wrong checksum ✘",
+                        migration.file_name
+                    );
+                    return Err(MigrationError::ChecksumMismatch);
+                }
+                previous_version = version;
+                continue;
+            }
+
+            plan.push((version, previous_version, migration));
+            previous_version = version;
+        }
+
+        if plan.is_empty() {
+            info!("No pending migrations to apply");
+            return Ok(());
+        }
+
+        info!("Applying {} pending migrations atomically", plan.len());
+
+        // Run every migration's statements in one transaction so a failure
+        // rolls the whole batch back. The bookkeeping nodes are data writes and
+        // cannot share this transaction with schema DDL, so they are recorded
+        // afterwards, once the batch has committed.
+        let mut tx = driver.start_txn_on(db.clone()).await?;
+        for (_, _, migration) in &plan {
+            if let Err(err) = self.run_migration_statements(&mut tx, migration).await {
+                error!("[{}] BATCH ABORTED - rolling back ✘", migration.file_name);
+                return Err(MigrationError::BatchAborted {
+                    file_name: migration.file_name.clone(),
+                    source: Box::new(err),
+                });
+            }
+        }
+        tx.commit().await?;
+
+        for (version, previous, migration) in &plan {
+            self.write_migration_node(db.clone(), driver.clone(), *version, *previous, migration)
+                .await?;
+            info!("[{}] DONE - migrated ✓", migration.file_name);
+        }
+
+        Ok(())
+    }
+
+    /// Actual migration, run as the user Cypher then a separate bookkeeping
+    /// write.
+    ///
+    /// Neo4j forbids mixing schema and data updates in one transaction, and
+    /// migration Cypher is usually DDL (`CREATE CONSTRAINT` / `CREATE INDEX`),
+    /// so the tracking-node `CREATE` (a data write) must run in its own
+    /// transaction. This leaves a narrow window where a crash between the two
+    /// commits applies a migration without recording it.
     async fn create_migration_node(
         &self,
-        counter: u64,
+        version: u64,
+        previous_version: u64,
         db: Database,
         driver: Graph,
         migration: &FileMigration,
     ) -> Result<Option<MigrationsNode>, MigrationError> {
         let mut tx = driver.start_txn_on(db.clone()).await?;
+        self.run_migration_statements(&mut tx, migration).await?;
+        tx.commit().await?;
+
+        self.write_migration_node(db, driver, version, previous_version, migration)
+            .await?;
+
+        Ok(None)
+    }
 
+    /// Runs a migration's user statements on the supplied transaction without
+    /// committing, so the caller controls the commit boundary (a single step or
+    /// a whole batch).
+    async fn run_migration_statements(
+        &self,
+        tx: &mut Txn,
+        migration: &FileMigration,
+    ) -> Result<(), MigrationError> {
+        for statement in self.split_statements(&migration.cypher_text) {
+            tx.run(query(statement.as_str())).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Records the tracking node for an applied migration in its own
+    /// transaction, kept separate from the (usually DDL) migration itself.
+    async fn write_migration_node(
+        &self,
+        db: Database,
+        driver: Graph,
+        version: u64,
+        previous_version: u64,
+        migration: &FileMigration,
+    ) -> Result<(), MigrationError> {
         let new_node = MigrationsNode {
             checksum: migration.checksum.clone(),
             file_name: migration.file_name.clone(),
-            cypher_text: migration.file_name.clone(),
-            version: counter + 1,
+            cypher_text: migration.cypher_text.clone(),
+            down_cypher: migration.down_cypher.clone(),
+            down_checksum: migration.down_checksum.clone(),
+            version,
             timestamp: chrono::Utc::now(),
         };
 
         let parameterized_migration = parameterize(new_node);
         let migration_node_query = query(include_str!("cypher/create_migration_node.cypher"))
             .param("migrationNode", parameterized_migration)
-            .param("previousVersion", counter as i64);
+            .param("previousVersion", previous_version as i64);
 
-        tx.run(query(migration.cypher_text.as_str())).await?;
+        let mut tx = driver.start_txn_on(db.clone()).await?;
+        tx.run(migration_node_query).await?;
         tx.commit().await?;
 
-        let mut tx_migration_node = driver.start_txn_on(db.clone()).await?;
-
-        tx_migration_node.run(migration_node_query).await?;
-        tx_migration_node.commit().await?;
-
-        Ok(None)
+        Ok(())
     }
 
     /// Runs a query to look for a migration node with the same file name.
@@ -166,23 +532,196 @@ impl GraphMigrator {
         }
     }
 
+    /// Loads every applied migration node, ordered by ascending `version`.
+    async fn get_applied_migrations(
+        &self,
+        db: Database,
+        driver: Graph,
+    ) -> Result<Vec<MigrationsNode>, MigrationError> {
+        let mut tx = driver.start_txn_on(db.clone()).await?;
+
+        let q = query("MATCH (m:DataModelMigration) RETURN m ORDER BY m.version ASC");
+
+        let mut results = tx.execute(q).await?;
+
+        let nodes = all::<MigrationsNode>(&mut tx, &mut results).await?;
+        tx.commit().await?;
+
+        Ok(nodes)
+    }
+
+    /// Processes a single directory entry, returning a `FileMigration` when it is
+    /// either a recognised migration file or a directory holding an `up.cyp`.
+    fn process_entry(&self, path: PathBuf) -> Option<FileMigration> {
+        if path.is_dir() {
+            return self.process_dir(path);
+        }
+
+        self.process_file(path)
+    }
+
     /// Processes a single file and returns a `FileMigration` if it is a `.cyp` file.
     fn process_file(&self, file_path: PathBuf) -> Option<FileMigration> {
         if file_path.extension()? == "cyp" || file_path.extension()? == "cypher" {
             let file_name = file_path.file_name()?.to_string_lossy().to_string();
-            let cypher_text = fs::read_to_string(&file_path).ok()?;
-            let checksum = self.calculate_checksum(&cypher_text);
+            let contents = fs::read_to_string(&file_path).ok()?;
+            let (up_cypher, down_cypher) = self.split_up_down(&contents);
 
-            Some(FileMigration {
-                checksum,
-                file_name,
-                cypher_text,
-            })
+            Some(self.build_migration(file_name, up_cypher, down_cypher))
         } else {
             None
         }
     }
 
+    /// Processes a migration directory laid out as paired `up.cyp` / `down.cyp`
+    /// files (the `.cypher` extension is accepted too).
+    fn process_dir(&self, dir: PathBuf) -> Option<FileMigration> {
+        let up_cypher = ["up.cyp", "up.cypher"]
+            .into_iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.exists())
+            .and_then(|path| fs::read_to_string(path).ok())?;
+
+        let down_cypher = ["down.cyp", "down.cypher"]
+            .into_iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.exists())
+            .and_then(|path| fs::read_to_string(path).ok());
+
+        let file_name = dir.file_name()?.to_string_lossy().to_string();
+
+        Some(self.build_migration(file_name, up_cypher, down_cypher))
+    }
+
+    /// Splits a combined migration file on `-- UP` / `-- DOWN` delimiter lines.
+    ///
+    /// Files without a delimiter are treated as a single "up" script with no
+    /// down counterpart.
+    fn split_up_down(&self, contents: &str) -> (String, Option<String>) {
+        let mut up = String::new();
+        let mut down = String::new();
+        let mut in_down = false;
+        let mut saw_delimiter = false;
+
+        for line in contents.lines() {
+            match line.trim().to_ascii_uppercase().as_str() {
+                "-- UP" => {
+                    saw_delimiter = true;
+                    in_down = false;
+                }
+                "-- DOWN" => {
+                    saw_delimiter = true;
+                    in_down = true;
+                }
+                _ => {
+                    let target = if in_down { &mut down } else { &mut up };
+                    target.push_str(line);
+                    target.push('\n');
+                }
+            }
+        }
+
+        if !saw_delimiter {
+            return (contents.to_string(), None);
+        }
+
+        let down = if down.trim().is_empty() {
+            None
+        } else {
+            Some(down)
+        };
+
+        (up, down)
+    }
+
+    /// Builds a `FileMigration`, checksumming the up and (optional) down scripts.
+    fn build_migration(
+        &self,
+        file_name: String,
+        up_cypher: String,
+        down_cypher: Option<String>,
+    ) -> FileMigration {
+        let legacy_checksum = self.calculate_checksum(&up_cypher);
+
+        let up_cypher = self.strip_comments(&up_cypher);
+        let down_cypher = down_cypher.map(|text| self.strip_comments(&text));
+
+        let checksum = self.calculate_checksum(&up_cypher);
+        let down_checksum = down_cypher
+            .as_deref()
+            .map(|text| self.calculate_checksum(text));
+
+        FileMigration {
+            checksum,
+            file_name,
+            cypher_text: up_cypher,
+            down_cypher,
+            down_checksum,
+            legacy_checksum,
+        }
+    }
+
+    /// Removes full-line comments beginning with `--` or `//`.
+    ///
+    /// Statements themselves are left untouched; only comment-only lines are
+    /// dropped so the normalized text is stable across comment reformatting.
+    fn strip_comments(&self, cypher: &str) -> String {
+        cypher
+            .lines()
+            .filter(|line| {
+                let trimmed = line.trim_start();
+                !trimmed.starts_with("--") && !trimmed.starts_with("//")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Splits normalized Cypher into individual statements on the `;` separator.
+    ///
+    /// neo4rs runs a single statement per `query`, so a file holding several
+    /// statements must be split first. Semicolons inside string literals are
+    /// ignored and empty trailing fragments are discarded.
+    fn split_statements(&self, cypher: &str) -> Vec<String> {
+        let mut statements = Vec::new();
+        let mut current = String::new();
+        let mut quote: Option<char> = None;
+        let mut chars = cypher.chars();
+
+        while let Some(c) = chars.next() {
+            match quote {
+                Some(delimiter) => {
+                    current.push(c);
+                    if c == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            current.push(escaped);
+                        }
+                    } else if c == delimiter {
+                        quote = None;
+                    }
+                }
+                None => match c {
+                    '\'' | '"' | '`' => {
+                        quote = Some(c);
+                        current.push(c);
+                    }
+                    ';' => {
+                        if !current.trim().is_empty() {
+                            statements.push(current.trim().to_string());
+                        }
+                        current.clear();
+                    }
+                    _ => current.push(c),
+                },
+            }
+        }
+
+        if !current.trim().is_empty() {
+            statements.push(current.trim().to_string());
+        }
+
+        statements
+    }
+
     /// Calculates a checksum for the given text.
     fn calculate_checksum(&self, content: &str) -> String {
         sha256::digest(content).to_string()
@@ -194,3 +733,365 @@ impl Default for GraphMigrator {
         Self::new()
     }
 }
+
+/// A coded migration: an async handler that mutates the graph through a
+/// transaction the runner owns.
+type CodeMigration = Box<
+    dyn for<'a> Fn(
+            &'a mut Txn,
+        )
+            -> Pin<Box<dyn Future<Output = Result<(), MigrationError>> + Send + 'a>>
+        + Send
+        + Sync,
+>;
+
+/// Builder for a [`MigrationManager`] that mixes coded Rust migrations into the
+/// file-based chain.
+///
+/// Some migrations cannot be expressed as static Cypher - for example reading
+/// existing nodes, transforming them in Rust and writing them back. Register
+/// those as versioned closures; `build()` produces a runner that interleaves
+/// them with the `.cyp` files by version.
+pub struct MigrationManagerBuilder {
+    handlers: BTreeMap<u64, CodeMigration>,
+}
+
+impl MigrationManagerBuilder {
+    pub fn new() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a coded migration under `version`.
+    ///
+    /// The handler runs inside the same transaction that records its tracking
+    /// node, so a failure leaves neither the data change nor the bookkeeping
+    /// write committed.
+    /// Handlers return a boxed `Send` future directly: the `AsyncFn(..)` sugar
+    /// cannot express the `Send` bound the runner needs on the returned future,
+    /// so we ask for an explicit `Pin<Box<dyn Future + Send>>` instead.
+    pub fn register<F>(mut self, version: u64, migration: F) -> Self
+    where
+        F: for<'a> Fn(
+                &'a mut Txn,
+            )
+                -> Pin<Box<dyn Future<Output = Result<(), MigrationError>> + Send + 'a>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.handlers.insert(version, Box::new(migration));
+        self
+    }
+
+    /// Builds the runner.
+    pub fn build(self) -> MigrationManager {
+        MigrationManager {
+            migrator: GraphMigrator::new(),
+            handlers: self.handlers,
+        }
+    }
+}
+
+impl Default for MigrationManagerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Item in an interleaved migration plan, keyed by version.
+enum PlannedMigration<'a> {
+    File(&'a FileMigration),
+    Code(&'a CodeMigration),
+}
+
+/// Runs file-based and coded migrations as a single version-ordered chain.
+pub struct MigrationManager {
+    migrator: GraphMigrator,
+    handlers: BTreeMap<u64, CodeMigration>,
+}
+
+impl MigrationManager {
+    /// Runs every pending migration - coded and file-based - in ascending
+    /// version order.
+    ///
+    /// Coded and file migrations share one version space; a version claimed by
+    /// both is rejected as a duplicate. Each migration is checked against the
+    /// existing `MigrationsNode` chain and recorded exactly as a file migration
+    /// is, using a synthetic checksum for coded handlers.
+    pub async fn run_migrations(
+        &self,
+        db: Database,
+        driver: Graph,
+        migrations: Vec<FileMigration>,
+    ) -> Result<(), MigrationError> {
+        let ordered = self.migrator.order_migrations(migrations)?;
+
+        let mut plan: BTreeMap<u64, PlannedMigration> = BTreeMap::new();
+        for (version, migration) in &ordered {
+            if plan.insert(*version, PlannedMigration::File(migration)).is_some() {
+                return Err(MigrationError::DuplicateVersion(*version));
+            }
+        }
+        for (version, handler) in &self.handlers {
+            if plan.insert(*version, PlannedMigration::Code(handler)).is_some() {
+                return Err(MigrationError::DuplicateVersion(*version));
+            }
+        }
+
+        info!("Running migrations for {} steps", plan.len());
+
+        let mut previous_version = 0u64;
+        for (version, planned) in &plan {
+            match planned {
+                PlannedMigration::File(migration) => {
+                    self.migrator
+                        .up_migration(*version, previous_version, db.clone(), driver.clone(), migration)
+                        .await?;
+                }
+                PlannedMigration::Code(handler) => {
+                    self.run_code_migration(*version, previous_version, handler, db.clone(), driver.clone())
+                        .await?;
+                }
+            }
+            previous_version = *version;
+        }
+
+        Ok(())
+    }
+
+    /// Runs a coded migration and records its tracking node in one transaction.
+    async fn run_code_migration(
+        &self,
+        version: u64,
+        previous_version: u64,
+        handler: &CodeMigration,
+        db: Database,
+        driver: Graph,
+    ) -> Result<(), MigrationError> {
+        let file_name = Self::code_migration_name(version);
+        let checksum = Self::code_migration_checksum(version);
+
+        let existing = self
+            .migrator
+            .get_existing_migration(db.clone(), driver.clone(), &file_name)
+            .await?;
+
+        if let Some(existing_migration) = existing {
+            if existing_migration.checksum != checksum {
+                error!("[{}] CHECKSUM MISMATCH - wrong checksum ✘", file_name);
+                return Err(MigrationError::ChecksumMismatch);
+            }
+            info!("[{}] SKIP - up to date ☇", file_name);
+            return Ok(());
+        }
+
+        let new_node = MigrationsNode {
+            checksum,
+            // Coded migrations have no source Cypher; record the synthetic name
+            // as a human-readable marker instead.
+            file_name: file_name.clone(),
+            cypher_text: file_name.clone(),
+            down_cypher: None,
+            down_checksum: None,
+            version,
+            timestamp: chrono::Utc::now(),
+        };
+
+        let parameterized_migration = parameterize(new_node);
+        let migration_node_query = query(include_str!("cypher/create_migration_node.cypher"))
+            .param("migrationNode", parameterized_migration)
+            .param("previousVersion", previous_version as i64);
+
+        let mut tx = driver.start_txn_on(db.clone()).await?;
+        handler(&mut tx).await?;
+        tx.run(migration_node_query).await?;
+        tx.commit().await?;
+
+        info!("[{}] DONE - migrated ✓", file_name);
+
+        Ok(())
+    }
+
+    /// Synthetic file name used to track a coded migration in the chain.
+    fn code_migration_name(version: u64) -> String {
+        format!("__code_{version}")
+    }
+
+    /// Synthetic checksum recorded for a coded migration, since there is no
+    /// source text to hash.
+    fn code_migration_checksum(version: u64) -> String {
+        sha256::digest(format!("code-migration-{version}")).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_up_and_down_on_delimiters() {
+        let migrator = GraphMigrator::new();
+        let (up, down) = migrator.split_up_down(
+            "-- UP\nCREATE (:A);\n-- DOWN\nMATCH (a:A) DETACH DELETE a;\n",
+        );
+
+        assert_eq!(up.trim(), "CREATE (:A);");
+        assert_eq!(down.as_deref().map(str::trim), Some("MATCH (a:A) DETACH DELETE a;"));
+    }
+
+    #[test]
+    fn files_without_delimiters_have_no_down() {
+        let migrator = GraphMigrator::new();
+        let (up, down) = migrator.split_up_down("CREATE (:A);\n");
+
+        assert_eq!(up, "CREATE (:A);\n");
+        assert!(down.is_none());
+    }
+
+    #[test]
+    fn parses_integer_and_timestamp_version_prefixes() {
+        assert_eq!(GraphMigrator::parse_version("001_init.cyp").unwrap(), 1);
+        assert_eq!(GraphMigrator::parse_version("42_add_index.cypher").unwrap(), 42);
+
+        let timestamp = GraphMigrator::parse_version("2024-01-02-153000_create.cyp").unwrap();
+        assert_eq!(timestamp, 1_704_209_400);
+    }
+
+    #[test]
+    fn rejects_unparseable_version_prefix() {
+        assert!(matches!(
+            GraphMigrator::parse_version("create_thing.cyp"),
+            Err(MigrationError::UnparseableVersion(_))
+        ));
+    }
+
+    #[test]
+    fn order_migrations_rejects_duplicate_versions() {
+        let migrator = GraphMigrator::new();
+        let make = |name: &str| migrator.build_migration(name.to_string(), "CREATE (:A)".to_string(), None);
+
+        let err = migrator
+            .order_migrations(vec![make("001_a.cyp"), make("001_b.cyp")])
+            .unwrap_err();
+
+        assert!(matches!(err, MigrationError::DuplicateVersion(1)));
+    }
+
+    #[test]
+    fn splits_statements_ignoring_semicolons_in_literals() {
+        let migrator = GraphMigrator::new();
+        let statements = migrator.split_statements(
+            "CREATE (:A {name: 'a;b'});\nMATCH (n) SET n.note = \"x;y\" RETURN n;",
+        );
+
+        assert_eq!(
+            statements,
+            vec![
+                "CREATE (:A {name: 'a;b'})".to_string(),
+                "MATCH (n) SET n.note = \"x;y\" RETURN n".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_statements_discards_empty_trailing_fragments() {
+        let migrator = GraphMigrator::new();
+        let statements = migrator.split_statements("CREATE (:A);;   \n");
+
+        assert_eq!(statements, vec!["CREATE (:A)".to_string()]);
+    }
+
+    #[test]
+    fn strip_comments_removes_comment_only_lines() {
+        let migrator = GraphMigrator::new();
+        let stripped =
+            migrator.strip_comments("-- a comment\nCREATE (:A);\n// another\nCREATE (:B);\n");
+
+        assert_eq!(stripped, "CREATE (:A);\nCREATE (:B);");
+    }
+
+    fn applied_node(file_name: &str, checksum: &str) -> MigrationsNode {
+        MigrationsNode {
+            checksum: checksum.to_string(),
+            file_name: file_name.to_string(),
+            cypher_text: "CREATE (:A)".to_string(),
+            down_cypher: None,
+            down_checksum: None,
+            version: 1,
+            timestamp: "2024-01-02T15:30:00Z".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn classify_reports_pending_applied_and_drift() {
+        let migrator = GraphMigrator::new();
+        let file = migrator.build_migration("001_a.cyp".to_string(), "CREATE (:A)".to_string(), None);
+
+        assert_eq!(GraphMigrator::classify(&file, None), MigrationState::Pending);
+
+        let node = applied_node("001_a.cyp", &file.checksum);
+        assert_eq!(
+            GraphMigrator::classify(&file, Some(&node)),
+            MigrationState::Applied
+        );
+
+        let drifted = applied_node("001_a.cyp", "stale");
+        assert_eq!(
+            GraphMigrator::classify(&file, Some(&drifted)),
+            MigrationState::ChecksumDrift {
+                expected: "stale".to_string(),
+                found: file.checksum.clone(),
+            }
+        );
+    }
+
+    #[test]
+    fn classify_treats_legacy_checksum_as_applied() {
+        let migrator = GraphMigrator::new();
+        // A comment line makes the normalized checksum differ from the raw one.
+        let file = migrator.build_migration(
+            "001_a.cyp".to_string(),
+            "-- note\nCREATE (:A)".to_string(),
+            None,
+        );
+        assert_ne!(file.checksum, file.legacy_checksum);
+
+        let node = applied_node("001_a.cyp", &file.legacy_checksum);
+        assert_eq!(
+            GraphMigrator::classify(&file, Some(&node)),
+            MigrationState::Applied
+        );
+    }
+
+    #[test]
+    fn migrations_node_round_trips_optional_down() {
+        let node = MigrationsNode {
+            checksum: "abc".to_string(),
+            file_name: "001_init.cyp".to_string(),
+            cypher_text: "CREATE (:A)".to_string(),
+            down_cypher: None,
+            down_checksum: None,
+            version: 1,
+            timestamp: "2024-01-02T15:30:00Z".parse().unwrap(),
+        };
+
+        let json = serde_json::to_string(&node).unwrap();
+        let decoded: MigrationsNode = serde_json::from_str(&json).unwrap();
+        assert!(decoded.down_cypher.is_none());
+
+        // A node written before down migrations existed omits the property
+        // entirely; it must still decode back to `None` rather than error.
+        let legacy = r#"{
+            "checksum": "abc",
+            "file_name": "001_init.cyp",
+            "cypher_text": "CREATE (:A)",
+            "version": 1,
+            "timestamp": "2024-01-02T15:30:00Z"
+        }"#;
+        let decoded: MigrationsNode = serde_json::from_str(legacy).unwrap();
+        assert!(decoded.down_cypher.is_none());
+        assert!(decoded.down_checksum.is_none());
+    }
+}